@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rusttype::{GlyphId, Point, PositionedGlyph, Rect};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::{Dimensions, StorageImage};
+
+use Error;
+
+pub(crate) type FontId = usize;
+
+/// Width/height of each atlas page, in texels.
+const PAGE_SIZE: u32 = 1024;
+
+/// Shelves whose height is within this many texels of a requested glyph height are reused
+/// rather than opening a new shelf, trading a little wasted space for fewer, taller shelves.
+const SHELF_TOLERANCE: u32 = 4;
+
+/// Blank texels packed around every glyph so the outline ring samples in the fragment shader
+/// (`shader/frag.glsl`) never read a neighboring glyph's coverage. Must be at least as large as
+/// the largest `outline_width` any run asks for.
+const GUTTER: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: FontId,
+    glyph: GlyphId,
+    scale: (u32, u32),
+    subpixel: (u32, u32),
+}
+
+/// A horizontal strip of a page that glyphs are packed into left-to-right. Once a shelf is
+/// full, a new one is opened above it; once no shelf (new or old) has room, a new page opens.
+struct Shelf {
+    x: u32,
+    y: u32,
+    height: u32,
+}
+
+struct Page {
+    image: Arc<StorageImage<Format>>,
+    shelves: Vec<Shelf>,
+}
+
+impl Page {
+    fn new(device: &Arc<Device>, queue_family_id: u32) -> Result<Self, Error> {
+        let queue_family = device
+            .physical_device()
+            .queue_family_by_id(queue_family_id)
+            .expect("queue family belongs to this device");
+        let image = StorageImage::new(
+            Arc::clone(device),
+            Dimensions::Dim2d {
+                width: PAGE_SIZE,
+                height: PAGE_SIZE,
+            },
+            Format::R8Unorm,
+            Some(queue_family),
+        )?;
+        Ok(Page {
+            image,
+            shelves: Vec::new(),
+        })
+    }
+
+    /// Places a `width x height` glyph on the first shelf it fits, opening a new shelf (and
+    /// failing if the page is full) when none do. Returns the glyph's top-left texel, padded by
+    /// `GUTTER` on every side so neighboring glyphs never bleed into each other's outline
+    /// samples.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        pack_shelf(&mut self.shelves, width, height)
+    }
+}
+
+/// Host-side shelf packing, pulled out of `Page::allocate` so it can be exercised in tests
+/// without a `Device` to back the page's image.
+fn pack_shelf(shelves: &mut Vec<Shelf>, width: u32, height: u32) -> Option<(u32, u32)> {
+    let padded_width = width + 2 * GUTTER;
+    let padded_height = height + 2 * GUTTER;
+
+    for shelf in shelves.iter_mut() {
+        let fits_height = shelf.height >= padded_height
+            && shelf.height <= padded_height.saturating_add(SHELF_TOLERANCE);
+        if fits_height && shelf.x + padded_width <= PAGE_SIZE {
+            let x = shelf.x;
+            shelf.x += padded_width;
+            return Some((x + GUTTER, shelf.y + GUTTER));
+        }
+    }
+
+    let y = shelves
+        .iter()
+        .map(|shelf| shelf.y + shelf.height)
+        .max()
+        .unwrap_or(0);
+    if padded_width > PAGE_SIZE || y + padded_height > PAGE_SIZE {
+        return None;
+    }
+    shelves.push(Shelf {
+        x: padded_width,
+        y,
+        height: padded_height,
+    });
+    Some((GUTTER, y + GUTTER))
+}
+
+pub(crate) struct GpuCache<'font> {
+    device: Arc<Device>,
+    queue_family_id: u32,
+    pages: Vec<Page>,
+    rects: HashMap<GlyphKey, (u32, Rect<u32>)>,
+    _marker: ::std::marker::PhantomData<&'font ()>,
+}
+
+/// A rasterized glyph's coverage bitmap, staged in a host-visible buffer and ready to be
+/// copied into its allocated rect on the atlas page image.
+pub(crate) struct PendingUpload {
+    pub(crate) image: Arc<StorageImage<Format>>,
+    pub(crate) buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    pub(crate) offset: [u32; 3],
+    pub(crate) extent: [u32; 3],
+}
+
+impl<'font> GpuCache<'font> {
+    pub(crate) fn new(device: &Arc<Device>, queue_family_id: u32) -> Result<Self, Error> {
+        Ok(GpuCache {
+            device: Arc::clone(device),
+            queue_family_id,
+            pages: vec![Page::new(device, queue_family_id)?],
+            rects: HashMap::new(),
+            _marker: ::std::marker::PhantomData,
+        })
+    }
+
+    /// Images backing every atlas page so far, in page order, for binding as a
+    /// `sampler2DArray` (or descriptor array of sampled images).
+    pub(crate) fn pages(&self) -> impl Iterator<Item = Arc<StorageImage<Format>>> + '_ {
+        self.pages.iter().map(|page| Arc::clone(&page.image))
+    }
+
+    /// Size of one atlas texel in UV space, for offsetting outline ring samples in the
+    /// fragment shader.
+    pub(crate) fn texel_size(&self) -> [f32; 2] {
+        [1.0 / PAGE_SIZE as f32, 1.0 / PAGE_SIZE as f32]
+    }
+
+    /// Packs `glyph` into the atlas if it isn't cached already, opening a new shelf or page as
+    /// needed, and rasterizes its coverage bitmap into a staging buffer. Returns `None` when
+    /// the glyph was already cached (nothing new to upload) or has no visible extent.
+    pub(crate) fn queue_glyph(
+        &mut self,
+        font: FontId,
+        glyph: &PositionedGlyph<'font>,
+    ) -> Result<Option<PendingUpload>, Error> {
+        let key = glyph_key(font, glyph);
+        if self.rects.contains_key(&key) {
+            return Ok(None);
+        }
+
+        let bb = match glyph.pixel_bounding_box() {
+            Some(bb) => bb,
+            None => return Ok(None),
+        };
+        let (width, height) = (bb.width() as u32, bb.height() as u32);
+
+        let page = self.pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(index, page)| page.allocate(width, height).map(|origin| (index, origin)));
+
+        let (page_index, (x, y)) = match page {
+            Some(found) => found,
+            None => {
+                let mut page = Page::new(&self.device, self.queue_family_id)?;
+                let origin = page.allocate(width, height).ok_or(Error::GlyphTooLarge)?;
+                self.pages.push(page);
+                (self.pages.len() - 1, origin)
+            }
+        };
+
+        let rect = Rect {
+            min: Point { x, y },
+            max: Point {
+                x: x + width,
+                y: y + height,
+            },
+        };
+        self.rects.insert(key, (page_index as u32, rect));
+
+        // Rasterize into a buffer padded by `GUTTER` on every side so the gutter texels upload
+        // as zero coverage rather than being left as stale/garbage data in the page image.
+        let padded_width = width + 2 * GUTTER;
+        let padded_height = height + 2 * GUTTER;
+        let mut pixels = vec![0u8; (padded_width * padded_height) as usize];
+        glyph.draw(|dx, dy, coverage| {
+            let px = dx + GUTTER;
+            let py = dy + GUTTER;
+            pixels[(py * padded_width + px) as usize] = (coverage * 255.0) as u8;
+        });
+
+        let buffer = CpuAccessibleBuffer::from_iter(
+            Arc::clone(&self.device),
+            BufferUsage::transfer_source(),
+            false,
+            pixels.into_iter(),
+        )?;
+
+        Ok(Some(PendingUpload {
+            image: Arc::clone(&self.pages[page_index].image),
+            buffer,
+            offset: [x - GUTTER, y - GUTTER, 0],
+            extent: [padded_width, padded_height, 1],
+        }))
+    }
+
+    /// Looks up the atlas page and, within it, the UV rect and screen rect for an
+    /// already-queued glyph.
+    pub(crate) fn rect_for(
+        &self,
+        font: FontId,
+        glyph: &PositionedGlyph<'font>,
+    ) -> Result<Option<(u32, Rect<f32>, Rect<i32>)>, Error> {
+        let key = glyph_key(font, glyph);
+        let screen_rect = match glyph.pixel_bounding_box() {
+            Some(bb) => bb,
+            None => return Ok(None),
+        };
+
+        Ok(self.rects.get(&key).map(|&(page, texel_rect)| {
+            let uv_rect = Rect {
+                min: Point {
+                    x: texel_rect.min.x as f32 / PAGE_SIZE as f32,
+                    y: texel_rect.min.y as f32 / PAGE_SIZE as f32,
+                },
+                max: Point {
+                    x: texel_rect.max.x as f32 / PAGE_SIZE as f32,
+                    y: texel_rect.max.y as f32 / PAGE_SIZE as f32,
+                },
+            };
+            (page, uv_rect, screen_rect)
+        }))
+    }
+}
+
+fn glyph_key(font: FontId, glyph: &PositionedGlyph) -> GlyphKey {
+    let scale = glyph.scale();
+    let position = glyph.position();
+    GlyphKey {
+        font,
+        glyph: glyph.id(),
+        scale: (scale.x.to_bits(), scale.y.to_bits()),
+        subpixel: (
+            position.x.fract().to_bits(),
+            position.y.fract().to_bits(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn padded_rect(origin: (u32, u32), width: u32, height: u32) -> Rect<u32> {
+        let (x, y) = origin;
+        Rect {
+            min: Point {
+                x: x - GUTTER,
+                y: y - GUTTER,
+            },
+            max: Point {
+                x: x + width + GUTTER,
+                y: y + height + GUTTER,
+            },
+        }
+    }
+
+    fn overlaps(a: Rect<u32>, b: Rect<u32>) -> bool {
+        a.min.x < b.max.x && b.min.x < a.max.x && a.min.y < b.max.y && b.min.y < a.max.y
+    }
+
+    #[test]
+    fn reuses_shelf_within_tolerance() {
+        let mut shelves = Vec::new();
+        let first = pack_shelf(&mut shelves, 10, 10 + SHELF_TOLERANCE).unwrap();
+        let second = pack_shelf(&mut shelves, 10, 10).unwrap();
+
+        assert_eq!(shelves.len(), 1);
+        assert_eq!(first.1, second.1, "both glyphs should land on the same shelf");
+        assert_ne!(first.0, second.0, "glyphs on the same shelf get distinct x origins");
+    }
+
+    #[test]
+    fn opens_new_shelf_when_height_exceeds_tolerance() {
+        let mut shelves = Vec::new();
+        pack_shelf(&mut shelves, 10, 10).unwrap();
+        pack_shelf(&mut shelves, 10, 10 + SHELF_TOLERANCE + 1).unwrap();
+
+        assert_eq!(shelves.len(), 2, "a glyph outside the tolerance opens a new shelf");
+    }
+
+    #[test]
+    fn overflow_returns_none_when_glyph_cannot_fit_on_page() {
+        let mut shelves = Vec::new();
+        assert!(pack_shelf(&mut shelves, PAGE_SIZE, 10).is_none());
+
+        let mut shelves = Vec::new();
+        loop {
+            if pack_shelf(&mut shelves, 10, PAGE_SIZE / 4).is_none() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn allocated_rects_never_overlap() {
+        let mut shelves = Vec::new();
+        let glyphs = [(10, 12), (8, 12), (40, 30), (10, 12), (900, 50)];
+
+        let mut rects = Vec::new();
+        for &(width, height) in &glyphs {
+            if let Some(origin) = pack_shelf(&mut shelves, width, height) {
+                rects.push(padded_rect(origin, width, height));
+            }
+        }
+
+        for (i, &a) in rects.iter().enumerate() {
+            for (j, &b) in rects.iter().enumerate().skip(i + 1) {
+                assert!(
+                    !overlaps(a, b),
+                    "rects {} and {} (including gutter) overlap",
+                    i,
+                    j
+                );
+            }
+        }
+    }
+}
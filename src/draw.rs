@@ -1,4 +1,4 @@
-use std::iter;
+use std::slice;
 use std::sync::Arc;
 
 use vulkano::buffer::{BufferUsage, CpuBufferPool};
@@ -7,12 +7,15 @@ use vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool;
 use vulkano::descriptor::PipelineLayoutAbstract;
 use vulkano::device::Device;
 use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::depth_stencil::DepthStencil;
 use vulkano::pipeline::vertex::SingleInstanceBufferDefinition;
 use vulkano::pipeline::viewport::Viewport;
 use vulkano::pipeline::GraphicsPipeline;
 use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
 
-use {Error, GlyphData, GpuCache};
+use rusttype::{Point, Rect};
+
+use {Error, GlyphData, GpuCache, PendingUpload};
 
 #[derive(Debug)]
 struct Vertex {
@@ -22,9 +25,31 @@ struct Vertex {
     tex_br: [f32; 2],
     color: [f32; 4],
     z: f32,
+    page: u32,
+    outline_color: [f32; 4],
+    outline_width: f32,
+    mat0: [f32; 4],
+    mat1: [f32; 4],
+    mat2: [f32; 4],
+    mat3: [f32; 4],
 }
 
-impl_vertex! { Vertex, tl, br, tex_tl, tex_br, color, z }
+impl_vertex! {
+    Vertex,
+    tl,
+    br,
+    tex_tl,
+    tex_br,
+    color,
+    z,
+    page,
+    outline_color,
+    outline_width,
+    mat0,
+    mat1,
+    mat2,
+    mat3
+}
 
 #[allow(unused)]
 mod vs {
@@ -54,6 +79,7 @@ pub(crate) struct Draw {
     pipe: Pipeline,
     vbuf: CpuBufferPool<Vertex>,
     ubuf: CpuBufferPool<vs::ty::Data>,
+    fbuf: CpuBufferPool<fs::ty::FragData>,
     pool: FixedSizeDescriptorSetsPool<Pipeline>,
     sampler: Arc<Sampler>,
     ibuf: CpuBufferPool<DrawIndirectCommand>,
@@ -67,17 +93,28 @@ impl Draw {
         let vs = vs::Shader::load(Arc::clone(device))?;
         let fs = fs::Shader::load(Arc::clone(device))?;
 
+        // Only write/test depth when the caller's subpass actually has a depth attachment to
+        // write into; otherwise the pipeline would fail to build against that render pass.
+        let depth_stencil = if subpass.has_depth() {
+            DepthStencil::simple_depth_test()
+        } else {
+            DepthStencil::disabled()
+        };
+
         let pipe = Arc::new(GraphicsPipeline::start()
             .vertex_input(SingleInstanceBufferDefinition::<Vertex>::new())
             .vertex_shader(vs.main_entry_point(), ())
             .triangle_strip()
             .viewports_dynamic_scissors_irrelevant(1)
             .fragment_shader(fs.main_entry_point(), ())
+            .depth_stencil(depth_stencil)
+            .blend_alpha_blending()
             .render_pass(subpass)
             .build(Arc::clone(device))?);
 
         let vbuf = CpuBufferPool::new(Arc::clone(device), BufferUsage::vertex_buffer());
         let ubuf = CpuBufferPool::new(Arc::clone(device), BufferUsage::uniform_buffer());
+        let fbuf = CpuBufferPool::new(Arc::clone(device), BufferUsage::uniform_buffer());
         let ibuf = CpuBufferPool::new(Arc::clone(device), BufferUsage::indirect_buffer());
 
         let pool = FixedSizeDescriptorSetsPool::new(Arc::clone(&pipe), 0);
@@ -100,6 +137,7 @@ impl Draw {
             pipe,
             vbuf,
             ubuf,
+            fbuf,
             pool,
             sampler,
             ibuf,
@@ -110,26 +148,78 @@ impl Draw {
         &mut self,
         cmd: AutoCommandBufferBuilder,
         data: &GlyphData,
-        cache: &GpuCache,
-        transform: [[f32; 4]; 4],
+        cache: &mut GpuCache,
+        screen: [[f32; 4]; 4],
+        scale_factor: f32,
+        dimensions: [u32; 2],
+    ) -> Result<AutoCommandBufferBuilder, Error> {
+        self.draw_batch(
+            cmd,
+            slice::from_ref(data),
+            cache,
+            screen,
+            scale_factor,
+            dimensions,
+        )
+    }
+
+    /// Draws many text runs in a single `draw_indirect` submission, building one combined
+    /// instance buffer and one `DrawIndirectCommand` per run instead of issuing a separate
+    /// draw call for each. `screen` is the shared screen/projection matrix applied to every
+    /// run; each run's own `transform` is applied on top of it, per instance. `scale_factor`
+    /// snaps every glyph to the physical pixel grid so text stays crisp under HiDPI scaling.
+    pub(crate) fn draw_batch(
+        &mut self,
+        cmd: AutoCommandBufferBuilder,
+        data: &[GlyphData],
+        cache: &mut GpuCache,
+        screen: [[f32; 4]; 4],
+        scale_factor: f32,
         [w, h]: [u32; 2],
     ) -> Result<AutoCommandBufferBuilder, Error> {
-        let vertices = text_vertices(data, cache, (w as f32, h as f32))?;
-        let instance_count = vertices.len() as u32;
+        let mut vertices = Vec::new();
+        let mut commands = Vec::with_capacity(data.len());
+        let mut uploads = Vec::new();
+        for run in data {
+            let (run_vertices, run_uploads) =
+                text_vertices(run, cache, scale_factor, (w as f32, h as f32))?;
+            let first_instance = vertices.len() as u32;
+            let instance_count = run_vertices.len() as u32;
+            vertices.extend(run_vertices);
+            uploads.extend(run_uploads);
+            commands.push(DrawIndirectCommand {
+                vertex_count: 4,
+                instance_count,
+                first_vertex: 0,
+                first_instance,
+            });
+        }
+
+        let mut cmd = cmd;
+        for upload in uploads {
+            cmd = cmd.copy_buffer_to_image_dimensions(
+                upload.buffer,
+                upload.image,
+                upload.offset,
+                upload.extent,
+                0,
+                1,
+                0,
+            )?;
+        }
+
         let vbuf = self.vbuf.chunk(vertices)?;
-        let ubuf = self.ubuf.next(vs::ty::Data { transform })?;
-        let ibuf = self.ibuf.chunk(iter::once(DrawIndirectCommand {
-            vertex_count: 4,
-            instance_count,
-            first_vertex: 0,
-            first_instance: 0,
-        }))?;
-
-        let set = self.pool
-            .next()
-            .add_buffer(ubuf)?
-            .add_sampled_image(cache.image(), Arc::clone(&self.sampler))?
-            .build()?;
+        let ubuf = self.ubuf.next(vs::ty::Data { transform: screen })?;
+        let fbuf = self.fbuf.next(fs::ty::FragData {
+            texel_size: cache.texel_size(),
+        })?;
+        let ibuf = self.ibuf.chunk(commands)?;
+
+        let mut set_builder = self.pool.next().add_buffer(ubuf)?.enter_array()?;
+        for page in cache.pages() {
+            set_builder = set_builder.add_sampled_image(page, Arc::clone(&self.sampler))?;
+        }
+        let set = set_builder.leave_array()?.add_buffer(fbuf)?.build()?;
 
         let state = DynamicState {
             line_width: None,
@@ -147,12 +237,18 @@ impl Draw {
 
 fn text_vertices<'font>(
     data: &GlyphData,
-    cache: &GpuCache<'font>,
+    cache: &mut GpuCache<'font>,
+    scale_factor: f32,
     (screen_width, screen_height): (f32, f32),
-) -> Result<impl ExactSizeIterator<Item = Vertex>, Error> {
+) -> Result<(Vec<Vertex>, Vec<PendingUpload>), Error> {
     let mut vertices = Vec::with_capacity(data.glyphs.len());
+    let mut uploads = Vec::new();
     for gly in data.glyphs.iter() {
-        if let Some((mut uv_rect, screen_rect)) = cache.rect_for(data.font, gly)? {
+        if let Some(upload) = cache.queue_glyph(data.font, gly)? {
+            uploads.push(upload);
+        }
+        if let Some((page, uv_rect, screen_rect)) = cache.rect_for(data.font, gly)? {
+            let screen_rect = snap_to_pixel_grid(screen_rect, scale_factor);
             vertices.push(Vertex {
                 tl: [
                     to_ndc(screen_rect.min.x, screen_width),
@@ -166,12 +262,36 @@ fn text_vertices<'font>(
                 tex_br: [uv_rect.max.x, uv_rect.max.y],
                 color: data.color,
                 z: data.z,
+                page,
+                outline_color: data.outline_color,
+                outline_width: data.outline_width,
+                mat0: data.transform[0],
+                mat1: data.transform[1],
+                mat2: data.transform[2],
+                mat3: data.transform[3],
             });
         }
     }
-    Ok(vertices.into_iter())
+    Ok((vertices, uploads))
+}
+
+/// Snaps a glyph's screen rect to the physical pixel grid: both corners of the logical rect are
+/// scaled into physical pixels by `scale_factor` and then floored, so the origin lands on a
+/// device pixel and the extent scales consistently with it (rather than scaling the origin only
+/// and leaving the glyph's size at 1x, which is wrong for any `scale_factor != 1`).
+fn snap_to_pixel_grid(screen_rect: Rect<i32>, scale_factor: f32) -> Rect<f32> {
+    Rect {
+        min: Point {
+            x: (screen_rect.min.x as f32 * scale_factor).floor(),
+            y: (screen_rect.min.y as f32 * scale_factor).floor(),
+        },
+        max: Point {
+            x: (screen_rect.max.x as f32 * scale_factor).floor(),
+            y: (screen_rect.max.y as f32 * scale_factor).floor(),
+        },
+    }
 }
 
-fn to_ndc(x: i32, size: f32) -> f32 {
-    (2 * x) as f32 / size - 1.0
+fn to_ndc(x: f32, size: f32) -> f32 {
+    (2.0 * x) / size - 1.0
 }
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use vulkano::command_buffer::DrawIndirectError;
+use vulkano::descriptor::descriptor_set::{
+    PersistentDescriptorSetBuildError, PersistentDescriptorSetError,
+};
+use vulkano::memory::DeviceMemoryAllocError;
+use vulkano::pipeline::GraphicsPipelineCreationError;
+use vulkano::sampler::SamplerCreationError;
+use vulkano::OomError;
+
+#[derive(Debug)]
+pub enum Error {
+    Oom(OomError),
+    Pipeline(GraphicsPipelineCreationError),
+    Sampler(SamplerCreationError),
+    Memory(DeviceMemoryAllocError),
+    DescriptorSet(PersistentDescriptorSetError),
+    DescriptorSetBuild(PersistentDescriptorSetBuildError),
+    Draw(DrawIndirectError),
+    /// A single glyph was too large to fit on an empty atlas page.
+    GlyphTooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Oom(ref err) => write!(f, "{}", err),
+            Error::Pipeline(ref err) => write!(f, "{}", err),
+            Error::Sampler(ref err) => write!(f, "{}", err),
+            Error::Memory(ref err) => write!(f, "{}", err),
+            Error::DescriptorSet(ref err) => write!(f, "{}", err),
+            Error::DescriptorSetBuild(ref err) => write!(f, "{}", err),
+            Error::Draw(ref err) => write!(f, "{}", err),
+            Error::GlyphTooLarge => write!(f, "glyph does not fit on an empty atlas page"),
+        }
+    }
+}
+
+impl From<OomError> for Error {
+    fn from(err: OomError) -> Self {
+        Error::Oom(err)
+    }
+}
+
+impl From<GraphicsPipelineCreationError> for Error {
+    fn from(err: GraphicsPipelineCreationError) -> Self {
+        Error::Pipeline(err)
+    }
+}
+
+impl From<SamplerCreationError> for Error {
+    fn from(err: SamplerCreationError) -> Self {
+        Error::Sampler(err)
+    }
+}
+
+impl From<DeviceMemoryAllocError> for Error {
+    fn from(err: DeviceMemoryAllocError) -> Self {
+        Error::Memory(err)
+    }
+}
+
+impl From<PersistentDescriptorSetError> for Error {
+    fn from(err: PersistentDescriptorSetError) -> Self {
+        Error::DescriptorSet(err)
+    }
+}
+
+impl From<PersistentDescriptorSetBuildError> for Error {
+    fn from(err: PersistentDescriptorSetBuildError) -> Self {
+        Error::DescriptorSetBuild(err)
+    }
+}
+
+impl From<DrawIndirectError> for Error {
+    fn from(err: DrawIndirectError) -> Self {
+        Error::Draw(err)
+    }
+}
@@ -0,0 +1,69 @@
+#[macro_use]
+extern crate vulkano;
+#[macro_use]
+extern crate vulkano_shader_derive;
+extern crate rusttype;
+
+mod cache;
+mod draw;
+mod error;
+
+use std::sync::Arc;
+
+use rusttype::PositionedGlyph;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::{Device, Queue};
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+
+pub use error::Error;
+
+use cache::{FontId, GpuCache, PendingUpload};
+use draw::Draw;
+
+/// A single positioned, colored run of glyphs to be drawn together.
+///
+/// `transform` is applied to this run's glyphs only, independently of any other run drawn
+/// in the same `draw_batch` call, so individual runs can be translated, scaled, or rotated
+/// without re-laying out their glyphs.
+pub struct GlyphData<'font> {
+    pub font: FontId,
+    pub glyphs: Vec<PositionedGlyph<'font>>,
+    pub color: [f32; 4],
+    pub z: f32,
+    pub transform: [[f32; 4]; 4],
+    /// Color of the halo/outline drawn beneath the glyph, sampled from a ring of offset
+    /// coverage lookups around each texel. Leave `outline_width` at `0.0` to disable it.
+    pub outline_color: [f32; 4],
+    /// Radius of the outline, in atlas texels.
+    pub outline_width: f32,
+}
+
+pub struct GlyphBrush<'font> {
+    cache: GpuCache<'font>,
+    draw: Draw,
+}
+
+impl<'font> GlyphBrush<'font> {
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        subpass: Subpass<Arc<RenderPassAbstract + Send + Sync>>,
+    ) -> Result<Self, Error> {
+        Ok(GlyphBrush {
+            cache: GpuCache::new(device, queue.family().id())?,
+            draw: Draw::new(device, subpass)?,
+        })
+    }
+
+    pub fn draw(
+        &mut self,
+        cmd: AutoCommandBufferBuilder,
+        data: &GlyphData,
+        screen: [[f32; 4]; 4],
+        scale_factor: f32,
+        dimensions: [u32; 2],
+    ) -> Result<AutoCommandBufferBuilder, Error> {
+        self.draw
+            .draw(cmd, data, &mut self.cache, screen, scale_factor, dimensions)
+    }
+}